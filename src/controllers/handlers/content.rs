@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use actix::{Addr, ActorFutureExt, Handler, Message, ResponseActFuture, WrapFuture};
+
+use crate::{
+    controllers::sites::SitesController,
+    core::{address::Address, error::Error, io::ContentMod, site::Site},
+};
+
+/// Looks up a loaded site's actor address by a wrapper key JWT, via
+/// `SitesController::get_by_key`: the token string is looked up in the
+/// `nonce` map and its verified `sub` (address) claim must match the entry.
+/// `ajax_keys` is not consulted.
+#[derive(Message)]
+#[rtype(result = "Result<(Address, Addr<Site>), Error>")]
+pub struct GetSiteByKey {
+    pub key: String,
+}
+
+impl GetSiteByKey {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+impl Handler<GetSiteByKey> for SitesController {
+    type Result = Result<(Address, Addr<Site>), Error>;
+
+    fn handle(&mut self, msg: GetSiteByKey, _ctx: &mut Self::Context) -> Self::Result {
+        self.get_by_key(msg.key)
+    }
+}
+
+/// Runs the `add_file_to_content` -> `sign_content` -> `save_content`
+/// sequence against a running `Site` actor. Kept as a single message so the
+/// multipart upload handler in `plugins::web::upload` doesn't need mutable
+/// access to the actor's inner state, and `content.json` is re-signed and
+/// saved atomically from the actor's point of view.
+#[derive(Message)]
+#[rtype(result = "Result<(), Error>")]
+pub struct UpdateSiteContent {
+    pub file_path: PathBuf,
+    pub inner_path: String,
+    pub private_key: String,
+}
+
+impl UpdateSiteContent {
+    pub fn new(file_path: PathBuf, inner_path: String, private_key: String) -> Self {
+        Self {
+            file_path,
+            inner_path,
+            private_key,
+        }
+    }
+}
+
+impl Handler<UpdateSiteContent> for Site {
+    type Result = ResponseActFuture<Self, Result<(), Error>>;
+
+    fn handle(&mut self, msg: UpdateSiteContent, _ctx: &mut Self::Context) -> Self::Result {
+        // The mutation runs against a clone because these are `&mut self`
+        // async trait methods and the actor's `handle` can't hold `&mut
+        // self` across an `.await`. The clone becomes the actor's new state
+        // once it succeeds (`actor: &mut Site` below), so the live actor
+        // isn't left with stale in-memory content after a successful save.
+        let mut updated = self.clone();
+        let fut = async move {
+            updated.add_file_to_content(msg.file_path).await?;
+            updated
+                .sign_content(Some(&msg.inner_path), &msg.private_key)
+                .await?;
+            updated.save_content(Some(&msg.inner_path)).await?;
+            Ok(updated)
+        };
+        Box::pin(fut.into_actor(self).map(|result: Result<Site, Error>, actor, _ctx| {
+            match result {
+                Ok(updated) => {
+                    *actor = updated;
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            }
+        }))
+    }
+}
@@ -0,0 +1,365 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use actix::{Actor, Addr, Context, Handler, Message, ResponseFuture};
+use log::*;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Semaphore, task::spawn_blocking, time::sleep};
+
+use crate::{
+    controllers::sites::SitesController,
+    core::{address::Address, error::Error},
+    environment::ENV,
+};
+
+/// Work a [`JobContainer`] can be asked to perform. Kept as an enum (rather
+/// than one struct per job type) so new background job kinds can be added
+/// without touching the worker dispatch loop's signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Job {
+    DownloadSite { address: String, peers: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    Queued,
+    Running,
+    Failed,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub files_fetched: u64,
+    pub files_total: u64,
+    pub bytes_fetched: u64,
+}
+
+impl Default for JobProgress {
+    fn default() -> Self {
+        Self {
+            files_fetched: 0,
+            files_total: 0,
+            bytes_fetched: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub job: Job,
+    pub state: JobState,
+    pub retries: u32,
+    pub progress: JobProgress,
+}
+
+const MAX_RETRIES: u32 = 5;
+const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+fn jobs_path() -> PathBuf {
+    ENV.data_path.join("jobs.json")
+}
+
+/// Background worker pool for long-running site operations. Jobs are kept
+/// off the request path: a handler enqueues a [`Job`] and returns
+/// immediately, while `JobContainer` drives it to completion (with retry and
+/// backoff) and persists its state so in-flight jobs survive a restart.
+pub struct JobContainer {
+    pub jobs: HashMap<String, JobRecord>,
+    semaphore: &'static Semaphore,
+}
+
+impl JobContainer {
+    pub fn new() -> Self {
+        let jobs = Self::load_from_disk().unwrap_or_default();
+        // Leaked once for the process lifetime: the actor and every spawned
+        // worker task need a `'static` handle to bound concurrency.
+        let semaphore: &'static Semaphore =
+            Box::leak(Box::new(Semaphore::new(MAX_CONCURRENT_DOWNLOADS)));
+        Self { jobs, semaphore }
+    }
+
+    fn load_from_disk() -> Option<HashMap<String, JobRecord>> {
+        let raw = std::fs::read_to_string(jobs_path()).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    fn persist(&self) {
+        let path = jobs_path();
+        match serde_json::to_string_pretty(&self.jobs) {
+            Ok(raw) => {
+                if let Err(err) = std::fs::write(&path, raw) {
+                    error!("Failed to persist job state to {:?}: {}", path, err);
+                }
+            }
+            Err(err) => error!("Failed to serialize job state: {}", err),
+        }
+    }
+
+    pub fn progress_for(&self, address: &str) -> Option<JobRecord> {
+        self.jobs.get(address).cloned()
+    }
+}
+
+impl Actor for JobContainer {
+    type Context = Context<Self>;
+
+    /// Jobs persisted as `Queued`/`Running` were in flight when the process
+    /// last stopped; re-dispatch them now so "survives a restart" actually
+    /// holds, instead of leaving them stuck until something else enqueues
+    /// the same address again.
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let pending: Vec<(String, Job, u32)> = self
+            .jobs
+            .iter()
+            .filter(|(_, record)| {
+                matches!(record.state, JobState::Queued | JobState::Running)
+            })
+            .map(|(address, record)| (address.clone(), record.job.clone(), record.retries))
+            .collect();
+        for (address, job, retries) in pending {
+            match job {
+                Job::DownloadSite { peers, .. } => {
+                    info!("Re-enqueuing download job for {} after restart", address);
+                    if let Some(record) = self.jobs.get_mut(&address) {
+                        record.state = JobState::Queued;
+                    }
+                    run_download_job(ctx.address(), self.semaphore, address, peers, retries);
+                }
+            }
+        }
+    }
+}
+
+/// Enqueues a `DownloadSite` job for `address`, returning immediately.
+#[derive(Message)]
+#[rtype(result = "Result<(), Error>")]
+pub struct DownloadSite {
+    pub address: Address,
+    pub peers: Vec<String>,
+}
+
+impl DownloadSite {
+    pub fn new(address: Address, peers: Vec<String>) -> Self {
+        Self { address, peers }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<JobRecord>")]
+pub struct GetJobStatus {
+    pub address: String,
+}
+
+impl Handler<DownloadSite> for JobContainer {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DownloadSite, ctx: &mut Self::Context) -> Self::Result {
+        let address = msg.address.address.clone();
+        if matches!(
+            self.jobs.get(&address).map(|r| r.state),
+            Some(JobState::Queued) | Some(JobState::Running)
+        ) {
+            trace!("Download for {} already in flight, skipping enqueue", address);
+            return Ok(());
+        }
+        self.jobs.insert(
+            address.clone(),
+            JobRecord {
+                job: Job::DownloadSite {
+                    address: address.clone(),
+                    peers: msg.peers.clone(),
+                },
+                state: JobState::Queued,
+                retries: 0,
+                progress: JobProgress::default(),
+            },
+        );
+        self.persist();
+        run_download_job(ctx.address(), self.semaphore, address, msg.peers, 0);
+        Ok(())
+    }
+}
+
+impl Handler<GetJobStatus> for JobContainer {
+    type Result = Option<JobRecord>;
+
+    fn handle(&mut self, msg: GetJobStatus, _ctx: &mut Self::Context) -> Self::Result {
+        self.progress_for(&msg.address)
+    }
+}
+
+/// A failed download attempt. `retryable` distinguishes a transient failure
+/// (bad peer, timeout) worth retrying with backoff from a permanent one
+/// (the fetch pipeline itself isn't wired up) that retrying can never fix.
+struct DownloadFailure {
+    message: String,
+    retryable: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct JobFinished {
+    address: String,
+    outcome: Result<(), DownloadFailure>,
+    retries: u32,
+}
+
+impl Handler<JobFinished> for JobContainer {
+    type Result = ();
+
+    fn handle(&mut self, msg: JobFinished, ctx: &mut Self::Context) -> Self::Result {
+        match msg.outcome {
+            Ok(()) => {
+                if let Some(record) = self.jobs.get_mut(&msg.address) {
+                    record.state = JobState::Done;
+                }
+                info!("Download job for {} completed", msg.address);
+            }
+            Err(failure) if failure.retryable && msg.retries < MAX_RETRIES => {
+                warn!(
+                    "Download job for {} failed ({}), retrying ({}/{})",
+                    msg.address,
+                    failure.message,
+                    msg.retries + 1,
+                    MAX_RETRIES
+                );
+                if let Some(record) = self.jobs.get_mut(&msg.address) {
+                    record.state = JobState::Queued;
+                    record.retries = msg.retries + 1;
+                }
+                if let Some(Job::DownloadSite { peers, .. }) =
+                    self.jobs.get(&msg.address).map(|r| r.job.clone())
+                {
+                    run_download_job(
+                        ctx.address(),
+                        self.semaphore,
+                        msg.address.clone(),
+                        peers,
+                        msg.retries + 1,
+                    );
+                }
+            }
+            Err(failure) => {
+                error!(
+                    "Download job for {} failed permanently after {} retries: {}",
+                    msg.address, msg.retries, failure.message
+                );
+                if let Some(record) = self.jobs.get_mut(&msg.address) {
+                    record.state = JobState::Failed;
+                }
+            }
+        }
+        self.persist();
+    }
+}
+
+/// Spawns the actual download as an async task bounded by `semaphore`, then
+/// reports the outcome back to the `JobContainer` actor. Backoff is
+/// exponential in the retry count, capped at 60s.
+fn run_download_job(
+    container: actix::Addr<JobContainer>,
+    semaphore: &'static Semaphore,
+    address: String,
+    peers: Vec<String>,
+    retries: u32,
+) {
+    actix::spawn(async move {
+        if retries > 0 {
+            let backoff = Duration::from_secs(2u64.saturating_pow(retries).min(60));
+            sleep(backoff).await;
+        }
+        let _permit = semaphore.acquire().await;
+        let outcome = download_site(&address, &peers).await;
+        let _ = container
+            .send(JobFinished {
+                address,
+                outcome,
+                retries,
+            })
+            .await;
+    });
+}
+
+/// Renders a downscaled preview of `source` and writes it to
+/// `cache_dir/cache_key`, bounded by the same worker-pool semaphore that
+/// limits concurrent downloads, so a large image never blocks an actix
+/// handler thread. See `plugins::web::preview` for the cache this backs.
+#[derive(Message)]
+#[rtype(result = "Result<PathBuf, Error>")]
+pub struct GeneratePreview {
+    pub source: PathBuf,
+    pub cache_dir: PathBuf,
+    pub cache_key: String,
+    pub max_dimension: u32,
+}
+
+impl Handler<GeneratePreview> for JobContainer {
+    type Result = ResponseFuture<Result<PathBuf, Error>>;
+
+    fn handle(&mut self, msg: GeneratePreview, _ctx: &mut Self::Context) -> Self::Result {
+        let semaphore = self.semaphore;
+        Box::pin(async move {
+            let _permit = semaphore.acquire().await;
+            fs::create_dir_all(&msg.cache_dir)
+                .await
+                .map_err(|_| Error::MissingError)?;
+            let dest = msg.cache_dir.join(&msg.cache_key);
+            let dest_for_blocking = dest.clone();
+            spawn_blocking(move || {
+                let image = image::open(&msg.source).map_err(|_| Error::MissingError)?;
+                let thumbnail = image.thumbnail(msg.max_dimension, msg.max_dimension);
+                // The cache key has no file extension, so the format is taken
+                // from the source path rather than inferred from `dest`.
+                let format =
+                    image::ImageFormat::from_path(&msg.source).map_err(|_| Error::MissingError)?;
+                thumbnail
+                    .save_with_format(&dest_for_blocking, format)
+                    .map_err(|_| Error::MissingError)
+            })
+            .await
+            .map_err(|_| Error::MissingError)??;
+            Ok(dest)
+        })
+    }
+}
+
+/// Returns the shared job queue's actor address, so web handlers holding
+/// only `Addr<SitesController>` can dispatch preview/download jobs.
+#[derive(Message)]
+#[rtype(result = "Addr<JobContainer>")]
+pub struct GetJobContainer;
+
+impl Handler<GetJobContainer> for SitesController {
+    type Result = Addr<JobContainer>;
+
+    fn handle(&mut self, _msg: GetJobContainer, _ctx: &mut Self::Context) -> Self::Result {
+        self.job_container().clone()
+    }
+}
+
+/// Not implemented: the actual peer-fetch (resolve peers for `address`, pull
+/// `content.json` and its referenced files) lives in the networking layer,
+/// which this tree doesn't have access to. An empty `peers` list is reported
+/// as a retryable failure, so such a job is retried up to `MAX_RETRIES`
+/// times with backoff. With peers, this returns a non-retryable failure
+/// rather than pretending the job might succeed on a later attempt. The queue/worker
+/// infrastructure above (persistence, retry, concurrency limits, restart
+/// recovery) is real and ready for a real fetch implementation to replace
+/// this function's body.
+async fn download_site(address: &str, peers: &[String]) -> Result<(), DownloadFailure> {
+    let site_dir = ENV.data_path.join(address);
+    fs::create_dir_all(&site_dir).await.map_err(|err| DownloadFailure {
+        message: err.to_string(),
+        retryable: true,
+    })?;
+    if peers.is_empty() {
+        return Err(DownloadFailure {
+            message: "no peers available to download from".to_string(),
+            retryable: true,
+        });
+    }
+    Err(DownloadFailure {
+        message: "peer-fetch pipeline is not implemented in this tree".to_string(),
+        retryable: false,
+    })
+}
@@ -0,0 +1,3 @@
+pub mod handlers;
+pub mod jobs;
+pub mod sites;
@@ -1,27 +1,35 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 use actix::{Actor, Addr};
 use futures::executor::block_on;
 use log::*;
-use rusqlite::{params, Connection};
+use rusqlite::{params_from_iter, Connection};
 use serde_json::{Map, Value};
 
 use crate::{
+    controllers::jobs::{DownloadSite, JobContainer},
     core::{
         address::Address,
         error::Error,
         io::SiteIO,
         site::{models::SiteStorage, Site},
+        store::store_for,
     },
     environment::{ENV, SITE_STORAGE},
     io::{db::DbManager, utils::current_unix_epoch},
+    plugins::web::auth_wrapper::verify_wrapper_token,
     utils::to_json_value,
 };
 
 pub async fn run() -> Result<Addr<SitesController>, Error> {
     info!("Starting Site Controller.");
     let db_manager = DbManager::new();
-    let mut site_controller = SitesController::new(db_manager);
+    let job_container = JobContainer::new().start();
+    let mut site_controller = SitesController::new(db_manager, job_container);
     let site_storage = &*SITE_STORAGE;
     site_controller
         .extend_sites_from_sitedata(site_storage.clone())
@@ -35,6 +43,15 @@ pub async fn run() -> Result<Addr<SitesController>, Error> {
     Ok(site_controller_addr)
 }
 
+/// Outcome of a `SitesController::get` lookup. A site whose `content.json`
+/// is not found is not an error: it is queued for background download and
+/// reported as `Downloading`. `Err` is returned only when loading a site
+/// whose content exists fails.
+pub enum SiteLookup {
+    Ready(Address, Addr<Site>),
+    Downloading,
+}
+
 pub struct SitesController {
     pub sites: HashMap<String, Site>,
     pub sites_addr: HashMap<Address, Addr<Site>>,
@@ -42,12 +59,14 @@ pub struct SitesController {
     pub nonce: HashMap<String, Address>,
     pub sites_changed: u64,
     pub db_manager: DbManager,
+    pub job_container: Addr<JobContainer>,
 }
 
 impl SitesController {
-    pub fn new(db_manager: DbManager) -> Self {
+    pub fn new(db_manager: DbManager, job_container: Addr<JobContainer>) -> Self {
         Self {
             db_manager,
+            job_container,
             sites: HashMap::new(),
             sites_addr: HashMap::new(),
             ajax_keys: HashMap::new(),
@@ -56,7 +75,7 @@ impl SitesController {
         }
     }
 
-    pub fn get(&mut self, address: Address) -> Result<(Address, Addr<Site>), Error> {
+    pub fn get(&mut self, address: Address) -> Result<SiteLookup, Error> {
         let address_str = address.address.clone();
         let mut site;
         let site = if let Some(site) = self.sites.get_mut(&address_str) {
@@ -65,19 +84,27 @@ impl SitesController {
             site = Site::new(&address_str, ENV.data_path.join(address_str.clone())).unwrap();
             &mut site
         };
+        // Only the existence check is routed through `SiteStore` so far;
+        // `site.load_content()` below still reads local disk regardless of
+        // `ENV.storage_backend` (see the gap noted on `core::store::S3Store`).
+        let content_exists = block_on(site.store().exists("content.json")).unwrap_or(false);
         if let Some(addr) = self.sites_addr.get(&address) {
-            if site.content_path().is_file() {
-                return Ok((address, addr.clone()));
+            if content_exists {
+                return Ok(SiteLookup::Ready(address, addr.clone()));
             }
         }
         trace!(
             "Spinning up actor for site zero://{}",
             address.get_address_short()
         );
-        if !site.content_path().is_file() {
-            // info!("Site content does not exist. Downloading...");
-            error!("\n\n\nSite content does not exist, Site Download from UiServer not implemented yet, Use siteDownload cmd via cli to download site\n\n\n");
-            unimplemented!();
+        if !content_exists {
+            info!(
+                "Site content for zero://{} not found locally, queuing background download",
+                address.get_address_short()
+            );
+            self.job_container
+                .do_send(DownloadSite::new(address.clone(), Vec::new()));
+            return Ok(SiteLookup::Downloading);
         } else {
             site.modify_storage(site.storage.clone());
             block_on(site.load_content())?;
@@ -97,14 +124,28 @@ impl SitesController {
         // TODO: Decide whether to spawn actors in syncArbiter
         let addr = site.clone().start();
         self.sites_addr.insert(address.clone(), addr.clone());
-        Ok((address, addr))
+        Ok(SiteLookup::Ready(address, addr))
     }
 
     pub fn get_by_key(&mut self, key: String) -> Result<(Address, Addr<Site>), Error> {
-        if let Some(address) = self.nonce.get(&key) {
-            if let Some(addr) = self.sites_addr.get(address) {
-                return Ok((address.clone(), addr.clone()));
+        // Wrapper keys are now always signed JWTs (see
+        // `plugins::web::auth_wrapper`): verify the signature/expiry and
+        // require the `sub` claim to match a live `self.nonce` entry, so
+        // that an expired or revoked (site removed from `self.nonce`) token
+        // never authenticates, even though it was recorded there at issue
+        // time. There is no opaque-nonce fallback anymore - every wrapper
+        // key handed out by `serve_auth_wrapper_key` is a JWT.
+        let claims = verify_wrapper_token(&key).map_err(|_| {
+            error!("Rejected expired or invalid wrapper token");
+            Error::MissingError
+        })?;
+        match self.nonce.get(&key) {
+            Some(address) if address.address == claims.sub => {
+                if let Some(addr) = self.sites_addr.get(address) {
+                    return Ok((address.clone(), addr.clone()));
+                }
             }
+            _ => {}
         }
         error!("No site found for key {}", key);
         Err(Error::MissingError)
@@ -131,7 +172,11 @@ impl SitesController {
     pub async fn extend_sites_from_sitedata(&mut self, sites: HashMap<String, SiteStorage>) {
         for (address, site_storage) in sites {
             let path = ENV.data_path.join(&address);
-            if path.exists() {
+            // Route the existence check through the configured `SiteStore`
+            // (same as `get()`) instead of a local `path.exists()`, so sites
+            // backed by S3 are picked up here too.
+            let content_exists = store_for(&address).exists("content.json").await.unwrap_or(false);
+            if content_exists {
                 let mut site = Site::new(&address, path).unwrap();
                 site.modify_storage(site_storage.clone());
                 let res = site.load_content().await;
@@ -142,12 +187,15 @@ impl SitesController {
                     self.ajax_keys
                         .insert(site_storage.keys.ajax_key, site.addr());
                 } else {
-                    //TODO! Start Downloading Site Content
                     error!(
-                        "Failed to load site {}, Error: {:?}",
+                        "Failed to load site {}, Error: {:?}. Queuing background download",
                         address,
                         res.unwrap_err()
                     );
+                    if let Ok(site_address) = Address::from_str(&address) {
+                        self.job_container
+                            .do_send(DownloadSite::new(site_address, Vec::new()));
+                    }
                 }
             } else {
                 warn!("Site Dir with Address: {} not found", address);
@@ -164,14 +212,144 @@ impl SitesController {
     fn update_sites_changed(&mut self) {
         self.sites_changed = current_unix_epoch();
     }
+
+    /// Handle to the background job queue, so callers (e.g. the UiServer)
+    /// can poll download progress for a site via `GetJobStatus`.
+    pub fn job_container(&self) -> &Addr<JobContainer> {
+        &self.job_container
+    }
+}
+
+/// Skips leading `--` line comments, `/* */` block comments, and whitespace,
+/// in any order, so the read-only guard below sees the statement's actual
+/// first keyword instead of being fooled by a comment in front of it.
+fn strip_leading_sql_comments(mut s: &str) -> &str {
+    loop {
+        s = s.trim_start();
+        if let Some(rest) = s.strip_prefix("--") {
+            match rest.find('\n') {
+                Some(idx) => {
+                    s = &rest[idx + 1..];
+                    continue;
+                }
+                None => return "",
+            }
+        }
+        if let Some(rest) = s.strip_prefix("/*") {
+            match rest.find("*/") {
+                Some(idx) => {
+                    s = &rest[idx + 2..];
+                    continue;
+                }
+                None => return "",
+            }
+        }
+        return s;
+    }
+}
+
+/// Whether `query` is a plain `SELECT` or a `WITH [RECURSIVE] ... SELECT`
+/// common table expression - the only statements ZeroNet's `dbQuery` surface
+/// exposes to site JS. Rather than a fixed-length prefix slice (which rejects
+/// legitimate CTEs), this walks top-level keywords (paren depth 0) past any
+/// `WITH`/`RECURSIVE`/CTE-name/`AS` tokens until it hits the statement's real
+/// action keyword, so a write hidden inside `WITH ... AS (...) INSERT ...`
+/// can't be mistaken for a read-only CTE either.
+fn is_read_only_statement(query: &str) -> bool {
+    let statement = strip_leading_sql_comments(query);
+    let mut depth = 0i32;
+    let mut word = String::new();
+    for c in statement.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        if word.is_empty() || depth != 0 {
+            word.clear();
+            continue;
+        }
+        let keyword = word.to_ascii_uppercase();
+        word.clear();
+        match keyword.as_str() {
+            "SELECT" => return true,
+            "INSERT" | "UPDATE" | "DELETE" | "REPLACE" | "DROP" | "ALTER" | "ATTACH"
+            | "DETACH" | "PRAGMA" | "VACUUM" | "CREATE" | "BEGIN" | "COMMIT" | "ROLLBACK"
+            | "REINDEX" => return false,
+            // `WITH`, `RECURSIVE`, CTE names, and `AS` aren't action keywords
+            // themselves - keep scanning for the statement's real verb.
+            _ => continue,
+        }
+    }
+    false
+}
+
+/// Bounds applied to a site-driven `dbQuery` call so a malicious or buggy
+/// site can't hang the controller or scrape an unbounded result set.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub row_limit: Option<usize>,
+    pub timeout: Option<Duration>,
 }
 
 impl SitesController {
+    /// Runs a site's `dbQuery` with bound parameters instead of interpolated
+    /// SQL. Only `SELECT` statements are allowed, matching the read-only
+    /// `dbQuery` surface ZeroNet's standard exposes to site JS; anything
+    /// else is rejected before it ever reaches `prepare`.
     pub async fn db_query(
         conn: &mut Connection,
         query: &str,
+        bound_params: &[rusqlite::types::Value],
+        options: QueryOptions,
     ) -> Result<Vec<Map<String, Value>>, Error> {
-        let mut stmt = conn.prepare(query).unwrap();
+        // ZeroNet's standard `dbQuery` surface is read-only: plain `SELECT`
+        // plus `WITH [RECURSIVE] ... SELECT ...` common table expressions.
+        // `is_read_only_statement` skips leading comments/whitespace and
+        // walks top-level (paren-depth 0) keywords so a CTE name or a
+        // `PRAGMA`/write statement hidden after `WITH` can't slip through.
+        if !is_read_only_statement(query) {
+            error!("Rejected non-SELECT dbQuery statement: {}", query);
+            return Err(Error::MissingError);
+        }
+
+        if let Some(timeout) = options.timeout {
+            let deadline = Instant::now() + timeout;
+            // Checked every 1000 VM instructions; cheap enough to not
+            // affect fast queries but bounds pathological ones.
+            conn.progress_handler(1000, Some(move || Instant::now() > deadline));
+        } else {
+            conn.progress_handler(0, None::<fn() -> bool>);
+        }
+
+        let result = Self::run_dbquery_statement(conn, query, bound_params, &options);
+
+        // The handler above captures `deadline`, which will have passed by
+        // the time a later statement runs - clear it regardless of outcome, or
+        // the next statement run on this (reused) `Connection` - e.g.
+        // `DbManager`'s schema/connect work - gets aborted immediately with
+        // SQLITE_INTERRUPT.
+        if options.timeout.is_some() {
+            conn.progress_handler(0, None::<fn() -> bool>);
+        }
+
+        result
+    }
+
+    fn run_dbquery_statement(
+        conn: &mut Connection,
+        query: &str,
+        bound_params: &[rusqlite::types::Value],
+        options: &QueryOptions,
+    ) -> Result<Vec<Map<String, Value>>, Error> {
+        let mut stmt = conn.prepare(query).map_err(|err| {
+            error!("Failed to prepare dbQuery {}: {}", query, err);
+            Error::MissingError
+        })?;
         let count = stmt.column_count();
         let names = {
             stmt.column_names()
@@ -179,8 +357,8 @@ impl SitesController {
                 .map(|s| s.to_string())
                 .collect::<Vec<String>>()
         };
-        let res = stmt
-            .query_map(params![], |row| {
+        let rows = stmt
+            .query_map(params_from_iter(bound_params.iter()), |row| {
                 let mut data_map = Map::new();
                 let mut i = 0;
                 loop {
@@ -196,8 +374,20 @@ impl SitesController {
                 }
                 Ok(data_map)
             })
-            .unwrap();
-        let res = res.filter_map(|e| e.ok()).collect::<Vec<_>>();
+            .map_err(|err| {
+                error!("Failed to run dbQuery {}: {}", query, err);
+                Error::MissingError
+            })?;
+
+        let mut res = Vec::new();
+        for row in rows.filter_map(|e| e.ok()) {
+            if let Some(limit) = options.row_limit {
+                if res.len() >= limit {
+                    break;
+                }
+            }
+            res.push(row);
+        }
         Ok(res)
     }
 }
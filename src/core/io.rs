@@ -1,8 +1,8 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use zerucontent::Content;
 
-use super::error::Error;
+use super::{error::Error, store::SiteStore};
 
 #[async_trait::async_trait]
 pub trait SiteIO {
@@ -10,6 +10,14 @@ pub trait SiteIO {
     fn content_path(&self) -> PathBuf;
     // async fn content(self) -> Result<Content, Error>;
     // async fn content_exists(&self) -> Result<bool, Error>;
+    /// The backing store for this site's files. Defaults to a
+    /// filesystem-backed store rooted at `site_path()`, but may be backed by
+    /// an object store instead (see `core::store`). Returns an owned, shared
+    /// handle rather than a borrow: building the S3 variant needs an async
+    /// client construction (memoized behind `store::store_for`'s `OnceCell`),
+    /// so a sync method can't hand back a `&dyn SiteStore` borrowed from
+    /// `self`.
+    fn store(&self) -> Arc<dyn SiteStore>;
     async fn init_download(&mut self) -> Result<bool, Error>;
     async fn load_storage(path: &str) -> Result<bool, Error>;
     async fn save_storage(&self) -> Result<bool, Error>;
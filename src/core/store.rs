@@ -0,0 +1,255 @@
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use futures::executor::block_on;
+use once_cell::sync::OnceCell;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::error::Error;
+use crate::environment::ENV;
+
+/// Abstract object store a site's files are read from and written to.
+/// `SiteIO` implementations hand off to one of these instead of touching the
+/// filesystem directly, so an operator can keep site content in an external
+/// object store (useful for horizontally scaling the UiServer, or keeping
+/// large sites off local disk) by pointing `ENV.storage_backend` at it.
+#[async_trait]
+pub trait SiteStore: Send + Sync {
+    async fn exists(&self, inner_path: &str) -> Result<bool, Error>;
+    async fn get(&self, inner_path: &str) -> Result<Box<dyn AsyncRead + Unpin + Send>, Error>;
+    async fn put(
+        &self,
+        inner_path: &str,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<(), Error>;
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+    async fn delete(&self, inner_path: &str) -> Result<(), Error>;
+}
+
+/// Joins a `list()` prefix with an entry name the same way across every
+/// `SiteStore` backend: `prefix/name`, or bare `name` when `prefix` is
+/// empty - so callers iterating `list()` see one key shape regardless of
+/// which backend is configured.
+fn join_prefix(prefix: &str, name: &str) -> String {
+    let trimmed = prefix.trim_end_matches('/');
+    if trimmed.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", trimmed, name)
+    }
+}
+
+/// Default backend: a site's files live under `<data_path>/<address>/`.
+pub struct LocalFileStore {
+    root: PathBuf,
+}
+
+impl LocalFileStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn resolve(&self, inner_path: &str) -> PathBuf {
+        self.root.join(inner_path)
+    }
+}
+
+#[async_trait]
+impl SiteStore for LocalFileStore {
+    async fn exists(&self, inner_path: &str) -> Result<bool, Error> {
+        Ok(tokio::fs::metadata(self.resolve(inner_path)).await.is_ok())
+    }
+
+    async fn get(&self, inner_path: &str) -> Result<Box<dyn AsyncRead + Unpin + Send>, Error> {
+        let file = tokio::fs::File::open(self.resolve(inner_path))
+            .await
+            .map_err(|_| Error::MissingError)?;
+        Ok(Box::new(file))
+    }
+
+    async fn put(
+        &self,
+        inner_path: &str,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<(), Error> {
+        let path = self.resolve(inner_path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|_| Error::MissingError)?;
+        }
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|_| Error::MissingError)?;
+        tokio::io::copy(reader, &mut file)
+            .await
+            .map_err(|_| Error::MissingError)?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut entries = tokio::fs::read_dir(self.resolve(prefix))
+            .await
+            .map_err(|_| Error::MissingError)?;
+        let mut names = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(join_prefix(prefix, name));
+            }
+        }
+        Ok(names)
+    }
+
+    async fn delete(&self, inner_path: &str) -> Result<(), Error> {
+        tokio::fs::remove_file(self.resolve(inner_path))
+            .await
+            .map_err(|_| Error::MissingError)
+    }
+}
+
+/// S3-compatible backend, selected via `ENV.storage_backend == "s3"`. Keeps
+/// site content in an external bucket instead of local disk.
+///
+/// Known gap: only the `SitesController::get`/`extend_sites_from_sitedata`
+/// existence checks have been routed through `SiteStore` so far. The actual
+/// content read (`Site::load_content`, in a part of the tree this series
+/// doesn't touch) still reads local disk unconditionally, so pointing
+/// `ENV.storage_backend` at `"s3"` today would make a site's existence check
+/// pass against the bucket while the subsequent load falls back to (and
+/// likely fails against) the local filesystem. `plugins::web::upload` also
+/// still writes uploads straight to local disk rather than through
+/// `SiteStore::put`. Treat `"s3"` as wired for the existence check only
+/// until `Site::load_content` and the upload path are migrated the same way.
+pub struct S3Store {
+    bucket: String,
+    prefix: String,
+    client: aws_sdk_s3::Client,
+}
+
+impl S3Store {
+    pub fn new(bucket: String, prefix: String, client: aws_sdk_s3::Client) -> Self {
+        Self {
+            bucket,
+            prefix,
+            client,
+        }
+    }
+
+    fn key(&self, inner_path: &str) -> String {
+        format!("{}/{}", self.prefix.trim_end_matches('/'), inner_path)
+    }
+}
+
+#[async_trait]
+impl SiteStore for S3Store {
+    async fn exists(&self, inner_path: &str) -> Result<bool, Error> {
+        Ok(self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.key(inner_path))
+            .send()
+            .await
+            .is_ok())
+    }
+
+    async fn get(&self, inner_path: &str) -> Result<Box<dyn AsyncRead + Unpin + Send>, Error> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.key(inner_path))
+            .send()
+            .await
+            .map_err(|_| Error::MissingError)?;
+        Ok(Box::new(object.body.into_async_read()))
+    }
+
+    async fn put(
+        &self,
+        inner_path: &str,
+        reader: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        reader
+            .read_to_end(&mut buf)
+            .await
+            .map_err(|_| Error::MissingError)?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.key(inner_path))
+            .body(buf.into())
+            .send()
+            .await
+            .map_err(|_| Error::MissingError)?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let resp = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(self.key(prefix))
+            .send()
+            .await
+            .map_err(|_| Error::MissingError)?;
+        // S3 keys come back as the full bucket key, including this store's
+        // own `self.prefix` root - strip it so callers see the same
+        // root-relative shape (`prefix/name`, no store-root segment) that
+        // `LocalFileStore::list` returns, instead of a format that differs
+        // per backend.
+        let own_root = format!("{}/", self.prefix.trim_end_matches('/'));
+        Ok(resp
+            .contents()
+            .iter()
+            .filter_map(|obj| obj.key())
+            .map(|key| {
+                key.strip_prefix(own_root.as_str())
+                    .map(|relative| relative.to_string())
+                    .unwrap_or_else(|| key.to_string())
+            })
+            .collect())
+    }
+
+    async fn delete(&self, inner_path: &str) -> Result<(), Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.key(inner_path))
+            .send()
+            .await
+            .map_err(|_| Error::MissingError)?;
+        Ok(())
+    }
+}
+
+/// The S3 client is the only piece of backend construction that's genuinely
+/// async (`aws_config::load_from_env`), and only needs doing once per
+/// process. Memoizing it here is what lets `store_for` below stay sync, so
+/// `SiteIO::store()` can call it without needing an async context itself.
+static S3_CLIENT: OnceCell<aws_sdk_s3::Client> = OnceCell::new();
+
+fn s3_client() -> aws_sdk_s3::Client {
+    S3_CLIENT
+        .get_or_init(|| {
+            let config = block_on(aws_config::load_from_env());
+            aws_sdk_s3::Client::new(&config)
+        })
+        .clone()
+}
+
+/// Picks a `SiteStore` for `address` based on `ENV.storage_backend`
+/// ("local" by default, or "s3"). Sync, so `SiteIO::store()` implementations
+/// can call it directly.
+pub fn store_for(address: &str) -> Arc<dyn SiteStore> {
+    match ENV.storage_backend.as_str() {
+        "s3" => Arc::new(S3Store::new(
+            ENV.s3_bucket.clone(),
+            address.to_string(),
+            s3_client(),
+        )),
+        _ => Arc::new(LocalFileStore::new(ENV.data_path.join(address))),
+    }
+}
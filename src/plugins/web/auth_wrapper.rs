@@ -5,15 +5,93 @@ use actix_web::{
     HttpRequest, HttpResponse,
 };
 use futures::executor::block_on;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use log::*;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     controllers::{handlers::sites::AddWrapperKey, server::ZeroServer},
-    core::address::Address,
+    core::{address::Address, error::Error},
     environment::ENV,
+    io::utils::current_unix_epoch,
 };
 
+/// Access level embedded in a wrapper token, checked by handlers that require
+/// more than read-only access (e.g. the multipart upload/signing endpoints).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    ReadOnly,
+    Admin,
+}
+
+/// Claims of the wrapper key JWT. `sub` is the authorized site address, so a
+/// verified token can be mapped straight onto `SitesController`'s existing
+/// `nonce` lookup without a second authentication mechanism.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrapperClaims {
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub scope: Scope,
+}
+
+const TOKEN_TTL_SECS: i64 = 60 * 60;
+
+/// Signs a wrapper key JWT for `address`, valid for [`TOKEN_TTL_SECS`].
+pub fn issue_wrapper_token(address: &str, scope: Scope) -> Result<String, Error> {
+    let iat = current_unix_epoch() as i64;
+    let claims = WrapperClaims {
+        sub: address.to_string(),
+        iat,
+        exp: iat + TOKEN_TTL_SECS,
+        scope,
+    };
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(ENV.jwt_secret.as_bytes()),
+    )
+    .map_err(|err| {
+        error!("Failed to sign wrapper token: {}", err);
+        Error::MissingError
+    })
+}
+
+/// Verifies the HS256 signature and expiry of a wrapper key JWT, returning
+/// its claims on success. Callers reject with 401 on any `Err`.
+pub fn verify_wrapper_token(token: &str) -> Result<WrapperClaims, Error> {
+    decode::<WrapperClaims>(
+        token,
+        &DecodingKey::from_secret(ENV.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+    .map_err(|err| {
+        trace!("Rejected wrapper token: {}", err);
+        Error::MissingError
+    })
+}
+
+/// Pulls the bearer token out of the `Authorization` header and verifies it.
+/// UiServer/API handlers that require an authenticated caller should call
+/// this before touching site state, and return 401 on `Err`. Returns the raw
+/// token alongside its claims since `SitesController::get_by_key` still
+/// looks callers up by the token string itself.
+pub fn authenticate_bearer(req: &HttpRequest) -> Result<(String, WrapperClaims), Error> {
+    let header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let token = header.strip_prefix("Bearer ").unwrap_or_default();
+    if token.is_empty() {
+        return Err(Error::MissingError);
+    }
+    verify_wrapper_token(token).map(|claims| (token.to_string(), claims))
+}
+
 pub async fn serve_auth_wrapper_key(
     req: HttpRequest,
     query: Query<HashMap<String, String>>,
@@ -21,6 +99,9 @@ pub async fn serve_auth_wrapper_key(
     let nonce = Uuid::new_v4().simple().to_string();
     let data = req.app_data::<Data<ZeroServer>>().unwrap();
     {
+        // `wrapper_nonces` is a separate CSRF-style guard the UiServer
+        // checks on wrapper page loads; it is unrelated to the JWT bearer
+        // token below and must keep being populated.
         let mut nonces = data.wrapper_nonces.lock().unwrap();
         nonces.insert(nonce.clone());
         trace!("Valid nonces ({}): {:?}", nonces.len(), nonces);
@@ -48,15 +129,22 @@ pub async fn serve_auth_wrapper_key(
             }
         }
     }
+    let token = match issue_wrapper_token(&address.address, Scope::Admin) {
+        Ok(token) => token,
+        Err(err) => {
+            error!("Failed to issue wrapper token: {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
     trace!("Serving wrapper key for {}", address);
     let result = data
         .site_controller
-        .send(AddWrapperKey::new(address.clone(), nonce.clone()));
+        .send(AddWrapperKey::new(address.clone(), token.clone()));
     let result = block_on(result);
 
     match result {
         Ok(_) => match result {
-            Ok(_) => return HttpResponse::Ok().body(format!("wrapper_key={}", nonce)),
+            Ok(_) => return HttpResponse::Ok().body(format!("wrapper_key={}", token)),
             Err(err) => {
                 error!("Bad request {}", err);
                 HttpResponse::BadRequest().finish()
@@ -0,0 +1,18 @@
+pub mod auth_wrapper;
+pub mod preview;
+pub mod upload;
+
+use actix_web::web;
+
+/// Registers this module's routes. Call from the main `App::new()` wiring
+/// alongside the rest of the UiServer's routes.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/ZeroNet-Internal/Upload",
+        web::post().to(upload::serve_upload_file),
+    );
+    cfg.route(
+        "/ZeroNet-Internal/Preview",
+        web::get().to(preview::serve_file_preview),
+    );
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use actix_web::{
+    web::{Data, Query},
+    HttpRequest, HttpResponse,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    controllers::{
+        jobs::{GeneratePreview, GetJobContainer},
+        server::ZeroServer,
+    },
+    core::address::Address,
+    environment::ENV,
+    plugins::web::auth_wrapper::authenticate_bearer,
+};
+
+const DEFAULT_PREVIEW_SIZE: u32 = 256;
+
+/// MIME families a previewer exists for. Only images are actually generated
+/// (see `GeneratePreview`'s handler, which calls `image::open`); anything
+/// else falls back to serving the original file untouched.
+fn is_previewable(mime: &str) -> bool {
+    mime.starts_with("image/")
+}
+
+fn cache_dir() -> PathBuf {
+    ENV.data_path.join("cache").join("previews")
+}
+
+fn hash_index_path() -> PathBuf {
+    cache_dir().join("hash_index.json")
+}
+
+/// Remembers a source file's sha512 keyed by its path, mtime and size, so a
+/// repeated request for an unchanged file doesn't have to re-read and
+/// re-hash it just to look up the cache key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HashIndexEntry {
+    modified: SystemTime,
+    len: u64,
+    sha512: String,
+}
+
+fn load_hash_index() -> HashMap<String, HashIndexEntry> {
+    std::fs::read_to_string(hash_index_path())
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn persist_hash_index(index: &HashMap<String, HashIndexEntry>) {
+    let path = hash_index_path();
+    if let Some(parent) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent) {
+            error!("Failed to create preview cache dir {:?}: {}", parent, err);
+            return;
+        }
+    }
+    match serde_json::to_string_pretty(index) {
+        Ok(raw) => {
+            if let Err(err) = std::fs::write(&path, raw) {
+                error!("Failed to persist preview hash index to {:?}: {}", path, err);
+            }
+        }
+        Err(err) => error!("Failed to serialize preview hash index: {}", err),
+    }
+}
+
+/// Sha512 of `source`, reused from `hash_index.json` when the file's mtime
+/// and size haven't changed since it was last computed. On an actual cache
+/// miss the file is streamed through the hasher in chunks rather than
+/// slurped into memory with `read_to_end`.
+async fn hash_of(source: &Path) -> Result<String, ()> {
+    let meta = tokio::fs::metadata(source).await.map_err(|_| ())?;
+    let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let len = meta.len();
+    let key = source.to_string_lossy().to_string();
+
+    let mut index = load_hash_index();
+    if let Some(entry) = index.get(&key) {
+        if entry.modified == modified && entry.len == len {
+            return Ok(entry.sha512.clone());
+        }
+    }
+
+    let mut file = tokio::fs::File::open(source).await.map_err(|_| ())?;
+    let mut hasher = Sha512::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).await.map_err(|_| ())?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    let sha512 = hex::encode(hasher.finalize());
+    index.insert(
+        key,
+        HashIndexEntry {
+            modified,
+            len,
+            sha512: sha512.clone(),
+        },
+    );
+    persist_hash_index(&index);
+    Ok(sha512)
+}
+
+/// Evicts least-recently-*used* cache entries (by file mtime) until the
+/// directory is back under `ENV.preview_cache_cap_bytes`. A cache hit in
+/// `serve_file_preview` touches the entry's mtime, so this is genuinely LRU
+/// rather than create-order eviction.
+async fn evict_if_over_cap() {
+    let dir = cache_dir();
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut files = Vec::new();
+    let mut total: u64 = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.path() == hash_index_path() {
+            continue;
+        }
+        if let Ok(meta) = entry.metadata().await {
+            total += meta.len();
+            let modified = meta.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+            files.push((entry.path(), meta.len(), modified));
+        }
+    }
+    if total <= ENV.preview_cache_cap_bytes {
+        return;
+    }
+    files.sort_by_key(|(_, _, modified)| *modified);
+    for (path, size, _) in files {
+        if total <= ENV.preview_cache_cap_bytes {
+            break;
+        }
+        if tokio::fs::remove_file(&path).await.is_ok() {
+            total = total.saturating_sub(size);
+        }
+    }
+}
+
+/// Serves a cached (or freshly generated) thumbnail for a file inside a
+/// site, keyed by `sha512(file):size`. Generation is dispatched to the
+/// background worker pool so a large image doesn't block the actix handler;
+/// MIME types without a previewer fall back to the original file.
+pub async fn serve_file_preview(
+    req: HttpRequest,
+    query: Query<HashMap<String, String>>,
+) -> HttpResponse {
+    if authenticate_bearer(&req).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let map = query.to_owned();
+    let def = String::default();
+    let address_string = map.get("address").unwrap_or(&def);
+    let address = match Address::from_str(address_string) {
+        Ok(address) => address,
+        Err(_) => return HttpResponse::BadRequest().body("malformed address"),
+    };
+    let inner_path = match map.get("inner_path") {
+        Some(path) => path.clone(),
+        None => return HttpResponse::BadRequest().body("missing inner_path"),
+    };
+    let size: u32 = map
+        .get("size")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_PREVIEW_SIZE);
+
+    let source = ENV.data_path.join(&address.address).join(&inner_path);
+    let mime = mime_guess::from_path(&inner_path)
+        .first_or_octet_stream()
+        .to_string();
+    if !is_previewable(&mime) {
+        return serve_original(&source, &mime).await;
+    }
+
+    let sha512 = match hash_of(&source).await {
+        Ok(sha512) => sha512,
+        Err(()) => return HttpResponse::NotFound().finish(),
+    };
+    let cache_key = format!("{}:{}", sha512, size);
+    let cache_path = cache_dir().join(&cache_key);
+
+    if tokio::fs::metadata(&cache_path).await.is_ok() {
+        touch(&cache_path).await;
+        return serve_original(&cache_path, &mime).await;
+    }
+
+    let data = req.app_data::<Data<ZeroServer>>().unwrap();
+    let job_container = match data.site_controller.send(GetJobContainer).await {
+        Ok(addr) => addr,
+        Err(err) => {
+            error!("Failed to reach job container: {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let result = job_container
+        .send(GeneratePreview {
+            source: source.clone(),
+            cache_dir: cache_dir(),
+            cache_key,
+            max_dimension: size,
+        })
+        .await;
+    match result {
+        Ok(Ok(generated_path)) => {
+            evict_if_over_cap().await;
+            serve_original(&generated_path, &mime).await
+        }
+        Ok(Err(err)) => {
+            warn!(
+                "Preview generation failed for {:?}, falling back to original: {}",
+                source, err
+            );
+            serve_original(&source, &mime).await
+        }
+        Err(err) => {
+            error!("Error dispatching preview job: {}", err);
+            serve_original(&source, &mime).await
+        }
+    }
+}
+
+/// Bumps a cache entry's mtime to "now" on a hit, so `evict_if_over_cap`'s
+/// mtime-ordered sweep evicts by actual last access instead of creation
+/// order. Runs via `spawn_blocking` rather than `block_in_place`:
+/// `block_in_place` panics outside a multi-threaded runtime, and actix-rt
+/// workers run one current-thread runtime each, so this would panic on
+/// every cache hit - the common fast path this function runs on.
+async fn touch(path: &Path) {
+    let path = path.to_path_buf();
+    let now = filetime::FileTime::now();
+    let result = tokio::task::spawn_blocking(move || filetime::set_file_mtime(&path, now)).await;
+    match result {
+        Ok(Err(err)) => warn!("Failed to touch preview cache entry: {}", err),
+        Err(err) => warn!("Preview cache touch task panicked: {}", err),
+        Ok(Ok(())) => {}
+    }
+}
+
+async fn serve_original(path: &Path, mime: &str) -> HttpResponse {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => HttpResponse::Ok().content_type(mime.to_string()).body(bytes),
+        Err(_) => HttpResponse::NotFound().finish(),
+    }
+}
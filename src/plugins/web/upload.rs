@@ -0,0 +1,177 @@
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
+
+use actix_multipart::Multipart;
+use actix_web::{
+    web::{Data, Query},
+    HttpRequest, HttpResponse,
+};
+use futures::{executor::block_on, StreamExt, TryStreamExt};
+use log::*;
+use sha2::{Digest, Sha512};
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{
+    controllers::{
+        handlers::content::{GetSiteByKey, UpdateSiteContent},
+        server::ZeroServer,
+    },
+    core::address::Address,
+    environment::ENV,
+    plugins::web::auth_wrapper::{authenticate_bearer, Scope},
+};
+
+/// Resolves `inner_path` against `site_dir`, rejecting anything that would
+/// escape it. This is a purely lexical, component-by-component check run
+/// before the path ever touches the filesystem: any `..`, absolute path, or
+/// platform prefix component is rejected outright, so there's nothing left
+/// for `canonicalize`/`starts_with` to get wrong by resolving `..` at
+/// syscall time against a directory that didn't exist yet when we checked.
+fn resolve_inner_path(site_dir: &std::path::Path, inner_path: &str) -> Result<PathBuf, ()> {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in std::path::Path::new(inner_path).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(()),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(());
+    }
+    Ok(site_dir.join(normalized))
+}
+
+/// Multipart upload endpoint: streams the uploaded file straight to disk
+/// (never buffering the whole body in memory), computes its sha512, moves it
+/// into place inside the site directory at `inner_path`, then re-signs and
+/// saves `content.json` via `ContentMod`.
+pub async fn serve_upload_file(
+    req: HttpRequest,
+    mut payload: Multipart,
+    query: Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let (token, claims) = match authenticate_bearer(&req) {
+        Ok(result) => result,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+    if claims.scope != Scope::Admin {
+        return HttpResponse::Forbidden().body("uploading requires an admin-scoped wrapper key");
+    }
+    let address = match Address::from_str(&claims.sub) {
+        Ok(address) => address,
+        Err(_) => return HttpResponse::BadRequest().body("malformed address in wrapper key"),
+    };
+
+    let map = query.to_owned();
+    let inner_path = match map.get("inner_path") {
+        Some(p) => p.clone(),
+        None => return HttpResponse::BadRequest().body("missing inner_path"),
+    };
+    // Never accept the site private key as a query param - it would land in
+    // access logs and proxy logs. Require it in a header instead.
+    let private_key = match req
+        .headers()
+        .get("X-Private-Key")
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(key) if !key.is_empty() => key.to_string(),
+        _ => return HttpResponse::BadRequest().body("missing X-Private-Key header"),
+    };
+
+    let site_dir = ENV.data_path.join(&address.address);
+    let target_path = match resolve_inner_path(&site_dir, &inner_path) {
+        Ok(path) => path,
+        Err(_) => {
+            return HttpResponse::BadRequest().body("inner_path escapes the site directory")
+        }
+    };
+
+    // Resolve the target site actor before touching the filesystem at all:
+    // if it isn't loaded there's nothing to sign the upload into, and we'd
+    // otherwise leave an unsigned orphan file behind in the site directory
+    // on this failure path.
+    let data = req.app_data::<Data<ZeroServer>>().unwrap();
+    let site_addr = match block_on(data.site_controller.send(GetSiteByKey::new(token))) {
+        Ok(Ok((_, site_addr))) => site_addr,
+        _ => return HttpResponse::NotFound().body("site is not loaded"),
+    };
+
+    if let Some(parent) = target_path.parent() {
+        if fs::create_dir_all(parent).await.is_err() {
+            return HttpResponse::InternalServerError().body("failed to prepare target directory");
+        }
+    }
+
+    let tmp_path = site_dir.join(format!(".upload-{}.tmp", Uuid::new_v4().simple()));
+    let mut tmp_file = match fs::File::create(&tmp_path).await {
+        Ok(file) => file,
+        Err(err) => {
+            error!("Failed to create temp upload file: {}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut hasher = Sha512::new();
+    loop {
+        let mut field = match payload.try_next().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                error!("Multipart stream error: {}", err);
+                let _ = fs::remove_file(&tmp_path).await;
+                return HttpResponse::BadRequest().body("malformed multipart upload");
+            }
+        };
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    error!("Multipart stream error: {}", err);
+                    let _ = fs::remove_file(&tmp_path).await;
+                    return HttpResponse::BadRequest().body("malformed multipart upload");
+                }
+            };
+            hasher.update(&chunk);
+            if tmp_file.write_all(&chunk).await.is_err() {
+                let _ = fs::remove_file(&tmp_path).await;
+                return HttpResponse::InternalServerError().body("failed to write upload to disk");
+            }
+        }
+    }
+    if tmp_file.flush().await.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
+        return HttpResponse::InternalServerError().finish();
+    }
+    let sha512 = hex::encode(hasher.finalize());
+
+    if fs::rename(&tmp_path, &target_path).await.is_err() {
+        error!(
+            "Failed to move upload into place at {:?}, leaving temp file {:?} for inspection",
+            target_path, tmp_path
+        );
+        return HttpResponse::InternalServerError().body("failed to store uploaded file");
+    }
+
+    let result = block_on(site_addr.send(UpdateSiteContent::new(
+        target_path,
+        inner_path.clone(),
+        private_key,
+    )));
+    match result {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "inner_path": inner_path,
+            "sha512": sha512,
+        })),
+        Ok(Err(err)) => {
+            error!("Failed to update content.json for upload: {}", err);
+            HttpResponse::InternalServerError().body("failed to sign and save content.json")
+        }
+        Err(err) => {
+            error!("Error sending content update to site actor: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}